@@ -28,10 +28,18 @@ fn main() {
     }
     let data = Bencoding::import(&mut BufReader::new(File::open(input_path).unwrap())).unwrap();
 
+    if let Some(hash) = data.info_hash() {
+        println!("Info Hash: {}", hex_string(&hash));
+    }
+
     let structure = print_list(0, data);
     println!("{}", structure);
 }
 
+fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
 fn print_list(nb_spaces: u32, bencoded: Bencoding) -> String {
     let mut text = String::new();
     match bencoded {
@@ -0,0 +1,250 @@
+//! Pull-based, streaming bencode decoding.
+//!
+//! `Decoder` yields one structural [`Event`] at a time instead of building a full `Bencoding`
+//! tree up front, so a caller can skip or hash a large string (e.g. a torrent's `pieces` field)
+//! without ever buffering the whole thing in one allocation. `Bencoding::import` is implemented
+//! on top of this layer for backward compatibility.
+
+use error::DecodeError;
+use std::io::{ErrorKind, Read};
+
+/// A single structural event produced by a [`Decoder`].
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    /// The start of a string of the given length. The caller must consume exactly that many
+    /// bytes, through [`Decoder::read_string_bytes`] or repeated calls to
+    /// [`Decoder::read_string_chunk`], before requesting the next event.
+    StringStart(usize),
+    Integer(i64),
+    ListStart,
+    DictStart,
+    /// The end of the innermost open list or dictionary.
+    End,
+}
+
+/// Reads structural [`Event`]s from a Reader one at a time, tracking how many bytes have been
+/// consumed so that a `DecodeError` can point at the exact byte offset of the corrupt region.
+pub struct Decoder<'a, R: Read + 'a> {
+    reader: &'a mut R,
+    offset: usize,
+    pending_string: usize,
+}
+
+impl<'a, R: Read + 'a> Decoder<'a, R> {
+    pub fn new(reader: &'a mut R) -> Decoder<'a, R> {
+        Decoder {
+            reader,
+            offset: 0,
+            pending_string: 0,
+        }
+    }
+
+    /// How many bytes have been consumed from the underlying Reader so far.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Reads the next structural event, or `None` if the Reader had no more data at all (used to
+    /// detect the end of a stream of concatenated top-level values).
+    pub fn next_event(&mut self) -> Result<Option<Event>, DecodeError> {
+        let first_char = match self.try_read_u8()? {
+            Some(b) => char::from(b),
+            None => return Ok(None),
+        };
+        match first_char {
+            '0'...'9' => {
+                let length = self.read_length(first_char)?;
+                self.pending_string = length;
+                Ok(Some(Event::StringStart(length)))
+            }
+            'i' => Ok(Some(Event::Integer(self.read_integer()?))),
+            'l' => Ok(Some(Event::ListStart)),
+            'd' => Ok(Some(Event::DictStart)),
+            'e' => Ok(Some(Event::End)),
+            _ => Err(DecodeError::UnknownSymbol(first_char, self.offset)),
+        }
+    }
+
+    /// Reads the full body of the string announced by the last `Event::StringStart`.
+    pub fn read_string_bytes(&mut self) -> Result<Vec<u8>, DecodeError> {
+        let mut data = vec![0u8; self.pending_string];
+        self.read_exact(&mut data)?;
+        self.pending_string = 0;
+        Ok(data)
+    }
+
+    /// Reads up to `buf.len()` bytes of the pending string into `buf[..n]`, returning `n`, the
+    /// number of bytes actually written this call. Lets a caller hash or skip a large string in
+    /// fixed-size chunks instead of allocating it all at once; call repeatedly until `n` is less
+    /// than `buf.len()` or `0`, which signals the string is exhausted.
+    pub fn read_string_chunk(&mut self, buf: &mut [u8]) -> Result<usize, DecodeError> {
+        let take = buf.len().min(self.pending_string);
+        self.read_exact(&mut buf[..take])?;
+        self.pending_string -= take;
+        Ok(take)
+    }
+
+    fn read_length(&mut self, first_char: char) -> Result<usize, DecodeError> {
+        let mut text = String::new();
+        text.push(first_char);
+        loop {
+            let chr = char::from(self.read_u8()?);
+            match chr {
+                '0'...'9' => text.push(chr),
+                ':' => break,
+                _ => return Err(DecodeError::InvalidNumberInteger(chr, self.offset)),
+            }
+        }
+        text.parse()
+            .map_err(|_| DecodeError::NumberOverflow(self.offset))
+    }
+
+    fn read_integer(&mut self) -> Result<i64, DecodeError> {
+        let mut text = String::new();
+        let first_chr = char::from(self.read_u8()?);
+        let second_chr = char::from(self.read_u8()?);
+        match first_chr {
+            '0' => {
+                if second_chr == 'e' {
+                    return Ok(0);
+                } else {
+                    return Err(DecodeError::LeadingZeroInteger(self.offset));
+                }
+            }
+            '-' => {
+                if second_chr == '0' {
+                    return Err(DecodeError::NegativeZeroInteger(self.offset));
+                }
+            }
+            _ => {
+                if !first_chr.is_digit(10) {
+                    return Err(DecodeError::InvalidNumberInteger(first_chr, self.offset));
+                }
+                if second_chr == 'e' {
+                    text.push(first_chr);
+                    return text
+                        .parse()
+                        .map_err(|_| DecodeError::NumberOverflow(self.offset));
+                }
+            }
+        }
+        if !second_chr.is_digit(10) {
+            return Err(DecodeError::InvalidNumberInteger(second_chr, self.offset));
+        }
+        text.push(first_chr);
+        text.push(second_chr);
+        loop {
+            let chr = char::from(self.read_u8()?);
+            match chr {
+                '0'...'9' => text.push(chr),
+                'e' => break,
+                _ => return Err(DecodeError::InvalidNumberInteger(chr, self.offset)),
+            }
+        }
+        text.parse()
+            .map_err(|_| DecodeError::NumberOverflow(self.offset))
+    }
+
+    fn try_read_u8(&mut self) -> Result<Option<u8>, DecodeError> {
+        let mut buf = [0u8; 1];
+        match self.reader.read_exact(&mut buf) {
+            Ok(()) => {
+                self.offset += 1;
+                Ok(Some(buf[0]))
+            }
+            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(DecodeError::IO(e, self.offset)),
+        }
+    }
+
+    fn read_u8(&mut self) -> Result<u8, DecodeError> {
+        match self.try_read_u8()? {
+            Some(b) => Ok(b),
+            None => Err(DecodeError::UnexpectedEof(self.offset)),
+        }
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), DecodeError> {
+        match self.reader.read_exact(buf) {
+            Ok(()) => {
+                self.offset += buf.len();
+                Ok(())
+            }
+            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => {
+                Err(DecodeError::UnexpectedEof(self.offset))
+            }
+            Err(e) => Err(DecodeError::IO(e, self.offset)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decoder, Event};
+    use error::DecodeError;
+    use std::io::Cursor;
+
+    #[test]
+    fn yields_string_event_then_its_bytes() {
+        let mut cursor = Cursor::new("4:spam".to_string());
+        let mut decoder = Decoder::new(&mut cursor);
+        assert_eq!(decoder.next_event().unwrap(), Some(Event::StringStart(4)));
+        assert_eq!(decoder.read_string_bytes().unwrap(), b"spam");
+        assert_eq!(decoder.next_event().unwrap(), None);
+    }
+
+    #[test]
+    fn string_can_be_read_in_chunks() {
+        let mut cursor = Cursor::new("6:foobar".to_string());
+        let mut decoder = Decoder::new(&mut cursor);
+        assert_eq!(decoder.next_event().unwrap(), Some(Event::StringStart(6)));
+        let mut buf = [0u8; 3];
+        assert_eq!(decoder.read_string_chunk(&mut buf).unwrap(), 3);
+        assert_eq!(&buf, b"foo");
+        assert_eq!(decoder.read_string_chunk(&mut buf).unwrap(), 3);
+        assert_eq!(&buf, b"bar");
+    }
+
+    #[test]
+    fn read_string_chunk_reports_bytes_written_on_a_short_final_chunk() {
+        let mut cursor = Cursor::new("6:foobar".to_string());
+        let mut decoder = Decoder::new(&mut cursor);
+        assert_eq!(decoder.next_event().unwrap(), Some(Event::StringStart(6)));
+        let mut buf = [0u8; 5];
+        assert_eq!(decoder.read_string_chunk(&mut buf).unwrap(), 5);
+        assert_eq!(&buf, b"fooba");
+        assert_eq!(decoder.read_string_chunk(&mut buf).unwrap(), 1);
+        assert_eq!(&buf[..1], b"r");
+    }
+
+    #[test]
+    fn oversized_string_length_reports_overflow_instead_of_panicking() {
+        let mut cursor = Cursor::new("99999999999999999999999999:x".to_string());
+        let mut decoder = Decoder::new(&mut cursor);
+        match decoder.next_event() {
+            Err(DecodeError::NumberOverflow(_)) => {}
+            other => panic!("Expected NumberOverflow error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn oversized_integer_reports_overflow_instead_of_panicking() {
+        let mut cursor = Cursor::new("i99999999999999999999999999e".to_string());
+        let mut decoder = Decoder::new(&mut cursor);
+        match decoder.next_event() {
+            Err(DecodeError::NumberOverflow(_)) => {}
+            other => panic!("Expected NumberOverflow error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn yields_list_and_dict_markers() {
+        let mut cursor = Cursor::new("ldei3ee".to_string());
+        let mut decoder = Decoder::new(&mut cursor);
+        assert_eq!(decoder.next_event().unwrap(), Some(Event::ListStart));
+        assert_eq!(decoder.next_event().unwrap(), Some(Event::DictStart));
+        assert_eq!(decoder.next_event().unwrap(), Some(Event::End));
+        assert_eq!(decoder.next_event().unwrap(), Some(Event::Integer(3)));
+        assert_eq!(decoder.next_event().unwrap(), Some(Event::End));
+    }
+}
@@ -5,31 +5,52 @@ use Bencoding;
 
 #[derive(Debug)]
 pub enum DecodeError {
-    IO(IOError),
-    UnknownSymbol(char),
-    LeadingZeroInteger,
-    NegativeZeroInteger,
-    InvalidNumberInteger(char),
-    KeyNotStringDictionary(Bencoding),
+    IO(IOError, usize),
+    UnexpectedEof(usize),
+    UnknownSymbol(char, usize),
+    LeadingZeroInteger(usize),
+    NegativeZeroInteger(usize),
+    InvalidNumberInteger(char, usize),
+    KeyNotStringDictionary(Bencoding, usize),
+    UnorderedDictionaryKeys(Vec<u8>, usize),
+    /// A string-length or integer literal parsed as digits too long to fit in `usize`/`i64`.
+    NumberOverflow(usize),
+    /// A `serde::Deserialize` implementation reported a domain error that has no associated
+    /// byte offset (e.g. a field of the wrong shape for the target type).
+    #[cfg(feature = "serde")]
+    Custom(String),
 }
 
 impl Error for DecodeError {
     fn description(&self) -> &str {
         match self {
-            DecodeError::IO(e) => e.description(),
-            DecodeError::UnknownSymbol(_) => {
+            DecodeError::IO(e, _) => e.description(),
+            DecodeError::UnexpectedEof(_) => {
+                "Reached the end of the input before finishing parsing a value"
+            }
+            DecodeError::UnknownSymbol(_, _) => {
                 "Failed to match symbol to get type of bencoded data to parse"
             }
-            DecodeError::LeadingZeroInteger => "A leading zero was read while parsing an integer",
-            DecodeError::NegativeZeroInteger => {
+            DecodeError::LeadingZeroInteger(_) => {
+                "A leading zero was read while parsing an integer"
+            }
+            DecodeError::NegativeZeroInteger(_) => {
                 "Read a negative zero was read while parsing an integer"
             }
-            DecodeError::InvalidNumberInteger(_) => {
+            DecodeError::InvalidNumberInteger(_, _) => {
                 "Read a character that cannot be interpreted as a number"
             }
-            DecodeError::KeyNotStringDictionary(_) => {
+            DecodeError::KeyNotStringDictionary(_, _) => {
                 "Expected a String as a key in a dictionary, found some other type"
             }
+            DecodeError::UnorderedDictionaryKeys(_, _) => {
+                "Dictionary keys were not in strictly increasing canonical order"
+            }
+            DecodeError::NumberOverflow(_) => {
+                "A length or integer literal was too large to fit in the target numeric type"
+            }
+            #[cfg(feature = "serde")]
+            DecodeError::Custom(_) => "A serde Deserialize implementation reported an error",
         }
     }
 }
@@ -37,20 +58,89 @@ impl Error for DecodeError {
 impl Display for DecodeError {
     fn fmt(&self, f: &mut Formatter) -> FMTResult {
         match self {
-            DecodeError::IO(e) => e.fmt(f),
-            DecodeError::UnknownSymbol(n) => write!(f, "{} could not be understood", n),
-            DecodeError::LeadingZeroInteger => write!(f, "Leading 0 before number"),
-            DecodeError::NegativeZeroInteger => write!(f, "Negative zero"),
-            DecodeError::InvalidNumberInteger(c) => write!(f, "{} is not a valid number", c),
-            DecodeError::KeyNotStringDictionary(k) => {
-                write!(f, "{:?} is not a correct key type", k)
+            DecodeError::IO(e, offset) => write!(f, "{} at byte {}", e, offset),
+            DecodeError::UnexpectedEof(offset) => {
+                write!(f, "Unexpected end of input at byte {}", offset)
+            }
+            DecodeError::UnknownSymbol(n, offset) => {
+                write!(f, "{} could not be understood at byte {}", n, offset)
+            }
+            DecodeError::LeadingZeroInteger(offset) => {
+                write!(f, "Leading 0 before number at byte {}", offset)
+            }
+            DecodeError::NegativeZeroInteger(offset) => {
+                write!(f, "Negative zero at byte {}", offset)
+            }
+            DecodeError::InvalidNumberInteger(c, offset) => {
+                write!(f, "{} is not a valid number at byte {}", c, offset)
+            }
+            DecodeError::KeyNotStringDictionary(k, offset) => {
+                write!(f, "{:?} is not a correct key type at byte {}", k, offset)
+            }
+            DecodeError::UnorderedDictionaryKeys(k, offset) => write!(
+                f,
+                "{:?} is out of canonical dictionary key order at byte {}",
+                k, offset
+            ),
+            DecodeError::NumberOverflow(offset) => {
+                write!(f, "Number literal out of range at byte {}", offset)
+            }
+            #[cfg(feature = "serde")]
+            DecodeError::Custom(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ::serde::de::Error for DecodeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        DecodeError::Custom(msg.to_string())
+    }
+}
+
+#[derive(Debug)]
+pub enum EncodeError {
+    IO(IOError),
+    LengthOverflow,
+    /// A `serde::Serialize` implementation reported a domain error (e.g. a map key that isn't a
+    /// string-like type, which bencode has no representation for).
+    #[cfg(feature = "serde")]
+    Custom(String),
+}
+
+impl Error for EncodeError {
+    fn description(&self) -> &str {
+        match self {
+            EncodeError::IO(e) => e.description(),
+            EncodeError::LengthOverflow => {
+                "A length or integer value could not be represented while encoding"
             }
+            #[cfg(feature = "serde")]
+            EncodeError::Custom(_) => "A serde Serialize implementation reported an error",
         }
     }
 }
 
-impl From<IOError> for DecodeError {
-    fn from(e: IOError) -> DecodeError {
-        DecodeError::IO(e)
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut Formatter) -> FMTResult {
+        match self {
+            EncodeError::IO(e) => e.fmt(f),
+            EncodeError::LengthOverflow => write!(f, "Length or integer value is out of range"),
+            #[cfg(feature = "serde")]
+            EncodeError::Custom(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<IOError> for EncodeError {
+    fn from(e: IOError) -> EncodeError {
+        EncodeError::IO(e)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ::serde::ser::Error for EncodeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        EncodeError::Custom(msg.to_string())
     }
 }
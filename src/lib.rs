@@ -1,161 +1,178 @@
-extern crate ez_io;
+extern crate sha1;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+#[macro_use]
+extern crate serde_derive;
 
+pub mod decoder;
 pub mod error;
+#[cfg(feature = "serde")]
+mod serde_impl;
 
-use error::DecodeError;
-use ez_io::ReadE;
-use std::collections::HashMap;
-use std::io::Read;
+use decoder::{Decoder, Event};
+use error::{DecodeError, EncodeError};
+use sha1::Sha1;
+#[cfg(feature = "serde")]
+pub use serde_impl::{from_bytes, to_bytes};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
 use std::result::Result;
 
 /// The primary type of this crate. This represents Bencoded data, that can be one of four types.
+///
+/// Dictionaries use a `BTreeMap` rather than a `HashMap` so that key order is always the raw-byte
+/// lexicographic order required by the spec, both when re-exporting and when walking the tree.
 #[derive(Clone, Debug)]
 pub enum Bencoding {
     String(Vec<u8>),
     Integer(i64),
     List(Vec<Bencoding>),
-    Dictionary(HashMap<Vec<u8>, Bencoding>),
+    Dictionary(BTreeMap<Vec<u8>, Bencoding>),
 }
 
 impl Bencoding {
     /// Imports Bencoded data through a Reader.
     pub fn import<R: Read>(reader: &mut R) -> Result<Bencoding, DecodeError> {
-        match decode(reader)? {
+        let mut decoder = Decoder::new(reader);
+        match build(&mut decoder)? {
             DecodeTypes::Bencoding(b) => Ok(b),
-            DecodeTypes::EndMarker => Err(DecodeError::UnknownSymbol('e')),
+            DecodeTypes::EndMarker => Err(DecodeError::UnknownSymbol('e', decoder.offset())),
         }
     }
-}
 
-enum DecodeTypes {
-    Bencoding(Bencoding),
-    EndMarker,
-}
-
-fn decode<R: Read>(reader: &mut R) -> Result<DecodeTypes, DecodeError> {
-    let type_character = char::from(reader.read_to_u8()?);
-    match type_character {
-        '0'...'9' => {
-            // String
-            Ok(DecodeTypes::Bencoding(Bencoding::String(decode_string(
-                type_character,
-                reader,
-            )?)))
-        }
-        'i' => {
-            // Integer
-            Ok(DecodeTypes::Bencoding(Bencoding::Integer(decode_integer(
-                reader,
-            )?)))
-        }
-        'l' => {
-            // List
-            Ok(DecodeTypes::Bencoding(Bencoding::List(decode_list(
-                reader,
-            )?)))
-        }
-        'd' => {
-            // Dictionary
-            Ok(DecodeTypes::Bencoding(Bencoding::Dictionary(decode_dict(
-                reader,
-            )?)))
-        }
-        'e' => {
-            // End Marker for Dicts and Lists
-            Ok(DecodeTypes::EndMarker)
-        }
-        _ => Err(DecodeError::UnknownSymbol(type_character)),
+    /// Exports this value to a Writer, using the canonical Bencoding form: dictionary keys are
+    /// always written out sorted by their raw byte value, as required by the BitTorrent spec for
+    /// the output to be valid and for `info_hash` computations to match other implementations.
+    pub fn export<W: Write>(&self, writer: &mut W) -> Result<(), EncodeError> {
+        encode(self, writer)
     }
-}
 
-fn decode_string<R: Read>(first_char: char, reader: &mut R) -> Result<Vec<u8>, DecodeError> {
-    let mut length_text = String::new();
-    length_text.push(first_char);
-    loop {
-        let chr = char::from(reader.read_to_u8()?);
-        match chr {
-            '0'...'9' => length_text.push(chr),
-            ':' => break,
-            _ => return Err(DecodeError::InvalidNumberInteger(chr)),
-        }
+    /// Computes the torrent info hash: the SHA-1 digest of the canonical encoding of the `info`
+    /// entry of this dictionary. Returns `None` if `self` is not a `Dictionary` or has no `info`
+    /// key.
+    pub fn info_hash(&self) -> Option<[u8; 20]> {
+        let info = match self {
+            Bencoding::Dictionary(d) => d.get(&b"info".to_vec())?,
+            _ => return None,
+        };
+        let mut buffer = Vec::new();
+        info.export(&mut buffer).ok()?;
+        let mut hasher = Sha1::new();
+        hasher.update(&buffer);
+        Some(hasher.digest().bytes())
     }
-    let length = length_text.parse::<usize>().unwrap(); // Can fail only if value is too big
-    let mut data = vec![0u8; length];
-    reader.read_exact(&mut data)?;
-    Ok(data)
 }
 
-fn decode_integer<R: Read>(reader: &mut R) -> Result<i64, DecodeError> {
-    let mut text = String::new();
-    let first_chr = char::from(reader.read_to_u8()?);
-    let second_chr = char::from(reader.read_to_u8()?);
-    match first_chr {
-        '0' => {
-            if second_chr == 'e' {
-                return Ok(0);
-            } else {
-                return Err(DecodeError::LeadingZeroInteger);
-            }
-        }
-        '-' => {
-            if second_chr == '0' {
-                return Err(DecodeError::NegativeZeroInteger);
-            }
-        }
-        _ => {
-            if second_chr == 'e' {
-                text.push(first_chr);
-                return Ok(text.parse().unwrap());
-            }
-        }
-    }
-    text.push(first_chr);
-    text.push(second_chr);
-    loop {
-        let chr = char::from(reader.read_to_u8()?);
-        match chr {
-            '0'...'9' => text.push(chr),
-            'e' => break,
-            _ => return Err(DecodeError::InvalidNumberInteger(chr)),
-        }
-    }
-    Ok(text.parse().unwrap()) // Can't fail
+enum DecodeTypes {
+    Bencoding(Bencoding),
+    EndMarker,
 }
 
-fn decode_list<R: Read>(reader: &mut R) -> Result<Vec<Bencoding>, DecodeError> {
-    let mut list = Vec::new();
-    loop {
-        let to_add = decode(reader)?;
-        match to_add {
-            DecodeTypes::EndMarker => break,
-            DecodeTypes::Bencoding(b) => list.push(b),
+/// Eagerly assembles a full `Bencoding` tree by pulling events from a `Decoder`, recursing into
+/// nested lists and dictionaries. This is how `Bencoding::import` is implemented on top of the
+/// streaming event layer.
+fn build<R: Read>(decoder: &mut Decoder<R>) -> Result<DecodeTypes, DecodeError> {
+    match decoder.next_event()? {
+        None => Err(DecodeError::UnexpectedEof(decoder.offset())),
+        Some(Event::End) => Ok(DecodeTypes::EndMarker),
+        Some(Event::Integer(i)) => Ok(DecodeTypes::Bencoding(Bencoding::Integer(i))),
+        Some(Event::StringStart(_)) => Ok(DecodeTypes::Bencoding(Bencoding::String(
+            decoder.read_string_bytes()?,
+        ))),
+        Some(Event::ListStart) => {
+            let mut list = Vec::new();
+            loop {
+                match build(decoder)? {
+                    DecodeTypes::EndMarker => break,
+                    DecodeTypes::Bencoding(b) => list.push(b),
+                }
+            }
+            Ok(DecodeTypes::Bencoding(Bencoding::List(list)))
         }
+        Some(Event::DictStart) => Ok(DecodeTypes::Bencoding(Bencoding::Dictionary(build_dict(
+            decoder,
+        )?))),
     }
-    Ok(list)
 }
 
-fn decode_dict<R: Read>(reader: &mut R) -> Result<HashMap<Vec<u8>, Bencoding>, DecodeError> {
-    let mut dict = HashMap::new();
+fn build_dict<R: Read>(
+    decoder: &mut Decoder<R>,
+) -> Result<BTreeMap<Vec<u8>, Bencoding>, DecodeError> {
+    let mut dict = BTreeMap::new();
+    let mut last_key: Option<Vec<u8>> = None;
     loop {
-        let key = match decode(reader)? {
+        let key = match build(decoder)? {
             DecodeTypes::Bencoding(b) => match b {
                 Bencoding::String(s) => s,
-                _ => return Err(DecodeError::KeyNotStringDictionary(b)),
+                _ => return Err(DecodeError::KeyNotStringDictionary(b, decoder.offset())),
             },
             DecodeTypes::EndMarker => break,
         };
-        let value = match decode(reader)? {
+        if let Some(previous) = last_key {
+            if key <= previous {
+                return Err(DecodeError::UnorderedDictionaryKeys(key, decoder.offset()));
+            }
+        }
+        last_key = Some(key.clone());
+        let value = match build(decoder)? {
             DecodeTypes::Bencoding(b) => b,
-            _ => return Err(DecodeError::UnknownSymbol('e')),
+            DecodeTypes::EndMarker => {
+                return Err(DecodeError::UnknownSymbol('e', decoder.offset()))
+            }
         };
         dict.insert(key, value);
     }
     Ok(dict)
 }
 
+fn encode<W: Write>(bencoded: &Bencoding, writer: &mut W) -> Result<(), EncodeError> {
+    match bencoded {
+        Bencoding::String(s) => encode_string(s, writer),
+        Bencoding::Integer(i) => encode_integer(*i, writer),
+        Bencoding::List(l) => encode_list(l, writer),
+        Bencoding::Dictionary(d) => encode_dict(d, writer),
+    }
+}
+
+fn encode_string<W: Write>(data: &[u8], writer: &mut W) -> Result<(), EncodeError> {
+    write!(writer, "{}:", data.len())?;
+    writer.write_all(data)?;
+    Ok(())
+}
+
+fn encode_integer<W: Write>(value: i64, writer: &mut W) -> Result<(), EncodeError> {
+    write!(writer, "i{}e", value)?;
+    Ok(())
+}
+
+fn encode_list<W: Write>(list: &[Bencoding], writer: &mut W) -> Result<(), EncodeError> {
+    writer.write_all(b"l")?;
+    for element in list {
+        encode(element, writer)?;
+    }
+    writer.write_all(b"e")?;
+    Ok(())
+}
+
+fn encode_dict<W: Write>(
+    dict: &BTreeMap<Vec<u8>, Bencoding>,
+    writer: &mut W,
+) -> Result<(), EncodeError> {
+    // BTreeMap already iterates in sorted key order, so no explicit sort is needed here.
+    writer.write_all(b"d")?;
+    for (key, value) in dict {
+        encode_string(key, writer)?;
+        encode(value, writer)?;
+    }
+    writer.write_all(b"e")?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use std::collections::BTreeMap;
     use std::io::Cursor;
     use Bencoding;
     #[test]
@@ -206,7 +223,7 @@ mod tests {
     fn dict_read() {
         match Bencoding::import(&mut Cursor::new("d4:spaml1:a1:bee".to_string())).unwrap() {
             Bencoding::Dictionary(d) => {
-                let mut dec_dict = HashMap::new();
+                let mut dec_dict = BTreeMap::new();
                 for (key, value) in d {
                     match value {
                         Bencoding::List(l) => {
@@ -223,7 +240,7 @@ mod tests {
                     }
                 }
                 assert_eq!(dec_dict, {
-                    let mut dict_cmp = HashMap::new();
+                    let mut dict_cmp = BTreeMap::new();
                     dict_cmp.insert(vec![b's', b'p', b'a', b'm'], vec![vec![b'a'], vec![b'b']]);
                     dict_cmp
                 });
@@ -231,4 +248,63 @@ mod tests {
             _ => panic!("Wrong type, should be Dict"),
         }
     }
+    #[test]
+    fn string_write() {
+        let mut buf = Vec::new();
+        Bencoding::String(vec![b's', b'p', b'a', b'm'])
+            .export(&mut buf)
+            .unwrap();
+        assert_eq!(buf, b"4:spam");
+    }
+    #[test]
+    fn integer_write() {
+        let mut buf = Vec::new();
+        Bencoding::Integer(-3).export(&mut buf).unwrap();
+        assert_eq!(buf, b"i-3e");
+    }
+    #[test]
+    fn list_write() {
+        let mut buf = Vec::new();
+        Bencoding::List(vec![
+            Bencoding::String(vec![b'a']),
+            Bencoding::String(vec![b'b']),
+        ]).export(&mut buf)
+        .unwrap();
+        assert_eq!(buf, b"l1:a1:be");
+    }
+    #[test]
+    fn dict_write_sorts_keys() {
+        let mut dict = BTreeMap::new();
+        dict.insert(vec![b'z'], Bencoding::Integer(1));
+        dict.insert(vec![b'a'], Bencoding::Integer(2));
+        let mut buf = Vec::new();
+        Bencoding::Dictionary(dict).export(&mut buf).unwrap();
+        assert_eq!(buf, b"d1:ai2e1:zi1ee");
+    }
+    #[test]
+    fn info_hash_of_info_dict() {
+        let bencoded = Bencoding::import(&mut Cursor::new(
+            "d4:infod6:lengthi3e4:name3:fooee".to_string(),
+        )).unwrap();
+        assert!(bencoded.info_hash().is_some());
+    }
+    #[test]
+    fn info_hash_without_info_key_is_none() {
+        let bencoded = Bencoding::import(&mut Cursor::new("d4:spam3:egge".to_string())).unwrap();
+        assert_eq!(bencoded.info_hash(), None);
+    }
+    #[test]
+    fn dict_read_rejects_unordered_keys() {
+        match Bencoding::import(&mut Cursor::new("d1:zi1e1:ai2ee".to_string())) {
+            Err(::error::DecodeError::UnorderedDictionaryKeys(k, _)) => assert_eq!(k, vec![b'a']),
+            _ => panic!("Expected UnorderedDictionaryKeys error"),
+        }
+    }
+    #[test]
+    fn truncated_input_reports_unexpected_eof() {
+        match Bencoding::import(&mut Cursor::new("4:sp".to_string())) {
+            Err(::error::DecodeError::UnexpectedEof(_)) => {}
+            _ => panic!("Expected UnexpectedEof error"),
+        }
+    }
 }
@@ -0,0 +1,549 @@
+//! `serde` Data Model integration, enabled by the `serde` feature.
+//!
+//! `Bencoding::String` maps to a byte buffer, `Integer` to `i64`, `List` to a sequence and
+//! `Dictionary` to a map, letting downstream consumers deserialize bencoded data directly into
+//! their own types instead of walking the `Bencoding` tree by hand.
+
+use error::{DecodeError, EncodeError};
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Cursor;
+use Bencoding;
+
+/// Deserializes a value of type `T` from a slice of bencoded bytes.
+pub fn from_bytes<'a, T: Deserialize<'a>>(bytes: &[u8]) -> Result<T, DecodeError> {
+    let bencoded = Bencoding::import(&mut Cursor::new(bytes))?;
+    T::deserialize(bencoded)
+}
+
+/// Serializes a value of type `T` to a `Vec<u8>` of bencoded bytes.
+pub fn to_bytes<T: Serialize>(value: &T) -> Result<Vec<u8>, EncodeError> {
+    let bencoded = require_present(value.serialize(BencodingSerializer)?)?;
+    let mut buffer = Vec::new();
+    bencoded.export(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// `None` only has a sensible bencode meaning as an absent struct/map field (see
+/// `BencodingMapSerializer`); anywhere else (a bare top-level value, a list element, a map key) it
+/// has nothing to be dropped from, so it's an error there.
+fn require_present(value: Option<Bencoding>) -> Result<Bencoding, EncodeError> {
+    value.ok_or_else(|| ser::Error::custom("bencode has no representation for None here"))
+}
+
+impl Serialize for Bencoding {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Bencoding::String(s) => serializer.serialize_bytes(s),
+            Bencoding::Integer(i) => serializer.serialize_i64(*i),
+            Bencoding::List(l) => {
+                let mut seq = serializer.serialize_seq(Some(l.len()))?;
+                for element in l {
+                    seq.serialize_element(element)?;
+                }
+                seq.end()
+            }
+            Bencoding::Dictionary(d) => {
+                let mut map = serializer.serialize_map(Some(d.len()))?;
+                for (key, value) in d {
+                    map.serialize_entry(&BencodingKey(key), value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+/// Serializes a raw dictionary key as bencode bytes rather than as a sequence of integers.
+struct BencodingKey<'a>(&'a [u8]);
+
+impl<'a> Serialize for BencodingKey<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Bencoding {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Bencoding, D::Error> {
+        deserializer.deserialize_any(BencodingVisitor)
+    }
+}
+
+struct BencodingVisitor;
+
+impl<'de> Visitor<'de> for BencodingVisitor {
+    type Value = Bencoding;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a bencoded string, integer, list or dictionary")
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Bencoding, E> {
+        Ok(Bencoding::Integer(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Bencoding, E> {
+        Ok(Bencoding::Integer(v as i64))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Bencoding, E> {
+        Ok(Bencoding::String(v.as_bytes().to_vec()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Bencoding, E> {
+        Ok(Bencoding::String(v.into_bytes()))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Bencoding, E> {
+        Ok(Bencoding::String(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Bencoding, E> {
+        Ok(Bencoding::String(v))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Bencoding, A::Error> {
+        let mut list = Vec::new();
+        while let Some(element) = seq.next_element()? {
+            list.push(element);
+        }
+        Ok(Bencoding::List(list))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Bencoding, A::Error> {
+        let mut dict = BTreeMap::new();
+        while let Some((key, value)) = map.next_entry::<Vec<u8>, Bencoding>()? {
+            dict.insert(key, value);
+        }
+        Ok(Bencoding::Dictionary(dict))
+    }
+}
+
+/// Lets a `Bencoding` value drive a `serde::Deserialize` implementation for a concrete type, by
+/// re-emitting its own shape as Deserializer calls.
+impl<'de> Deserializer<'de> for Bencoding {
+    type Error = DecodeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DecodeError> {
+        match self {
+            Bencoding::String(s) => visitor.visit_byte_buf(s),
+            Bencoding::Integer(i) => visitor.visit_i64(i),
+            Bencoding::List(l) => visitor.visit_seq(BencodingSeqAccess {
+                iter: l.into_iter(),
+            }),
+            Bencoding::Dictionary(d) => visitor.visit_map(BencodingMapAccess {
+                iter: d.into_iter(),
+                value: None,
+            }),
+        }
+    }
+
+    /// Bencode has no representation for absent values, so any `Bencoding` we hold is always
+    /// present; forwarding this to `deserialize_any` would route it through `Visitor::visit_*`
+    /// methods that `Option`'s visitor doesn't implement, so we call `visit_some` directly.
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DecodeError> {
+        visitor.visit_some(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf
+        unit unit_struct newtype_struct seq tuple tuple_struct map struct
+        enum identifier ignored_any
+    }
+}
+
+struct BencodingSeqAccess {
+    iter: ::std::vec::IntoIter<Bencoding>,
+}
+
+impl<'de> SeqAccess<'de> for BencodingSeqAccess {
+    type Error = DecodeError;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, DecodeError> {
+        match self.iter.next() {
+            Some(value) => seed.deserialize(value).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+struct BencodingMapAccess {
+    iter: ::std::collections::btree_map::IntoIter<Vec<u8>, Bencoding>,
+    value: Option<Bencoding>,
+}
+
+impl<'de> MapAccess<'de> for BencodingMapAccess {
+    type Error = DecodeError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, DecodeError> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(Bencoding::String(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, DecodeError> {
+        let value = self.value.take().expect("next_value called before next_key");
+        seed.deserialize(value)
+    }
+}
+
+/// Drives a `serde::Serialize` implementation for a concrete type into a `Bencoding` tree. Floats
+/// and unit values have no bencode representation, so they fail with `EncodeError`. `None` has no
+/// representation either, but since a struct or map field can simply be omitted, this serializer's
+/// `Ok` is `Option<Bencoding>` so `BencodingMapSerializer` can tell a `None` field apart from every
+/// other value and drop it instead of erroring; callers with nowhere to drop a value (a bare
+/// top-level value, a list element, a map key) go through `require_present` instead.
+struct BencodingSerializer;
+
+struct BencodingSeqSerializer {
+    items: Vec<Bencoding>,
+}
+
+struct BencodingMapSerializer {
+    dict: BTreeMap<Vec<u8>, Bencoding>,
+    next_key: Option<Vec<u8>>,
+}
+
+impl Serializer for BencodingSerializer {
+    type Ok = Option<Bencoding>;
+    type Error = EncodeError;
+    type SerializeSeq = BencodingSeqSerializer;
+    type SerializeTuple = BencodingSeqSerializer;
+    type SerializeTupleStruct = BencodingSeqSerializer;
+    type SerializeTupleVariant = BencodingSeqSerializer;
+    type SerializeMap = BencodingMapSerializer;
+    type SerializeStruct = BencodingMapSerializer;
+    type SerializeStructVariant = BencodingMapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Option<Bencoding>, EncodeError> {
+        Ok(Some(Bencoding::Integer(if v { 1 } else { 0 })))
+    }
+    fn serialize_i8(self, v: i8) -> Result<Option<Bencoding>, EncodeError> {
+        Ok(Some(Bencoding::Integer(i64::from(v))))
+    }
+    fn serialize_i16(self, v: i16) -> Result<Option<Bencoding>, EncodeError> {
+        Ok(Some(Bencoding::Integer(i64::from(v))))
+    }
+    fn serialize_i32(self, v: i32) -> Result<Option<Bencoding>, EncodeError> {
+        Ok(Some(Bencoding::Integer(i64::from(v))))
+    }
+    fn serialize_i64(self, v: i64) -> Result<Option<Bencoding>, EncodeError> {
+        Ok(Some(Bencoding::Integer(v)))
+    }
+    fn serialize_u8(self, v: u8) -> Result<Option<Bencoding>, EncodeError> {
+        Ok(Some(Bencoding::Integer(i64::from(v))))
+    }
+    fn serialize_u16(self, v: u16) -> Result<Option<Bencoding>, EncodeError> {
+        Ok(Some(Bencoding::Integer(i64::from(v))))
+    }
+    fn serialize_u32(self, v: u32) -> Result<Option<Bencoding>, EncodeError> {
+        Ok(Some(Bencoding::Integer(i64::from(v))))
+    }
+    fn serialize_u64(self, v: u64) -> Result<Option<Bencoding>, EncodeError> {
+        if v > i64::MAX as u64 {
+            return Err(EncodeError::LengthOverflow);
+        }
+        Ok(Some(Bencoding::Integer(v as i64)))
+    }
+    fn serialize_f32(self, _v: f32) -> Result<Option<Bencoding>, EncodeError> {
+        Err(ser::Error::custom("bencode has no floating point representation"))
+    }
+    fn serialize_f64(self, _v: f64) -> Result<Option<Bencoding>, EncodeError> {
+        Err(ser::Error::custom("bencode has no floating point representation"))
+    }
+    fn serialize_char(self, v: char) -> Result<Option<Bencoding>, EncodeError> {
+        let mut buf = [0u8; 4];
+        Ok(Some(Bencoding::String(
+            v.encode_utf8(&mut buf).as_bytes().to_vec(),
+        )))
+    }
+    fn serialize_str(self, v: &str) -> Result<Option<Bencoding>, EncodeError> {
+        Ok(Some(Bencoding::String(v.as_bytes().to_vec())))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<Option<Bencoding>, EncodeError> {
+        Ok(Some(Bencoding::String(v.to_vec())))
+    }
+    fn serialize_none(self) -> Result<Option<Bencoding>, EncodeError> {
+        Ok(None)
+    }
+    fn serialize_some<T: Serialize + ?Sized>(
+        self,
+        value: &T,
+    ) -> Result<Option<Bencoding>, EncodeError> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<Option<Bencoding>, EncodeError> {
+        Err(ser::Error::custom("bencode has no representation for ()"))
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Option<Bencoding>, EncodeError> {
+        self.serialize_unit()
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Option<Bencoding>, EncodeError> {
+        Ok(Some(Bencoding::String(variant.as_bytes().to_vec())))
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Option<Bencoding>, EncodeError> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Option<Bencoding>, EncodeError> {
+        let mut dict = BTreeMap::new();
+        dict.insert(
+            variant.as_bytes().to_vec(),
+            require_present(value.serialize(BencodingSerializer)?)?,
+        );
+        Ok(Some(Bencoding::Dictionary(dict)))
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<BencodingSeqSerializer, EncodeError> {
+        Ok(BencodingSeqSerializer {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+    fn serialize_tuple(self, len: usize) -> Result<BencodingSeqSerializer, EncodeError> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<BencodingSeqSerializer, EncodeError> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        len: usize,
+    ) -> Result<BencodingSeqSerializer, EncodeError> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<BencodingMapSerializer, EncodeError> {
+        Ok(BencodingMapSerializer {
+            dict: BTreeMap::new(),
+            next_key: None,
+        })
+    }
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<BencodingMapSerializer, EncodeError> {
+        self.serialize_map(Some(len))
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        len: usize,
+    ) -> Result<BencodingMapSerializer, EncodeError> {
+        self.serialize_map(Some(len))
+    }
+}
+
+impl SerializeSeq for BencodingSeqSerializer {
+    type Ok = Option<Bencoding>;
+    type Error = EncodeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), EncodeError> {
+        self.items
+            .push(require_present(value.serialize(BencodingSerializer)?)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Option<Bencoding>, EncodeError> {
+        Ok(Some(Bencoding::List(self.items)))
+    }
+}
+
+impl ser::SerializeTuple for BencodingSeqSerializer {
+    type Ok = Option<Bencoding>;
+    type Error = EncodeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), EncodeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Option<Bencoding>, EncodeError> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for BencodingSeqSerializer {
+    type Ok = Option<Bencoding>;
+    type Error = EncodeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), EncodeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Option<Bencoding>, EncodeError> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for BencodingSeqSerializer {
+    type Ok = Option<Bencoding>;
+    type Error = EncodeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), EncodeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Option<Bencoding>, EncodeError> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl SerializeMap for BencodingMapSerializer {
+    type Ok = Option<Bencoding>;
+    type Error = EncodeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), EncodeError> {
+        match require_present(key.serialize(BencodingSerializer)?)? {
+            Bencoding::String(s) => {
+                self.next_key = Some(s);
+                Ok(())
+            }
+            _ => Err(ser::Error::custom("bencode dictionary keys must be strings")),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), EncodeError> {
+        let key = self
+            .next_key
+            .take()
+            .expect("serialize_value called before serialize_key");
+        // A `None` value has nowhere to go but is not an error: the entry is simply omitted, the
+        // same as bencode/serde_bencode generally treat an absent optional field.
+        if let Some(value) = value.serialize(BencodingSerializer)? {
+            self.dict.insert(key, value);
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<Option<Bencoding>, EncodeError> {
+        Ok(Some(Bencoding::Dictionary(self.dict)))
+    }
+}
+
+impl ser::SerializeStruct for BencodingMapSerializer {
+    type Ok = Option<Bencoding>;
+    type Error = EncodeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), EncodeError> {
+        // Same reasoning as `SerializeMap::serialize_value`: an absent optional field is simply
+        // dropped from the dictionary rather than erroring.
+        if let Some(value) = value.serialize(BencodingSerializer)? {
+            self.dict.insert(key.as_bytes().to_vec(), value);
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<Option<Bencoding>, EncodeError> {
+        SerializeMap::end(self)
+    }
+}
+
+impl ser::SerializeStructVariant for BencodingMapSerializer {
+    type Ok = Option<Bencoding>;
+    type Error = EncodeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), EncodeError> {
+        ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<Option<Bencoding>, EncodeError> {
+        SerializeMap::end(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_bytes, to_bytes};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Torrent {
+        name: String,
+        length: i64,
+        pieces: Vec<String>,
+        comment: Option<String>,
+    }
+
+    #[test]
+    fn struct_round_trips_through_bencode() {
+        let torrent = Torrent {
+            name: "ubuntu.iso".to_string(),
+            length: 12345,
+            pieces: vec!["abc".to_string(), "def".to_string()],
+            comment: Some("a comment".to_string()),
+        };
+        let bytes = to_bytes(&torrent).unwrap();
+        let decoded: Torrent = from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, torrent);
+    }
+
+    #[test]
+    fn present_option_field_round_trips() {
+        let torrent = Torrent {
+            name: "foo".to_string(),
+            length: 1,
+            pieces: vec![],
+            comment: Some("present".to_string()),
+        };
+        let bytes = to_bytes(&torrent).unwrap();
+        let decoded: Torrent = from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.comment, Some("present".to_string()));
+    }
+
+    #[test]
+    fn none_option_field_is_omitted_and_round_trips() {
+        let torrent = Torrent {
+            name: "foo".to_string(),
+            length: 1,
+            pieces: vec![],
+            comment: None,
+        };
+        let bytes = to_bytes(&torrent).unwrap();
+        assert!(!bytes.windows(7).any(|w| w == b"comment"));
+        let decoded: Torrent = from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, torrent);
+    }
+}